@@ -1,19 +1,29 @@
 use axum::{
-    extract::{Path, State, WebSocketUpgrade, ws::{WebSocket, Message}},
+    extract::{ConnectInfo, Path, State, WebSocketUpgrade, ws::{WebSocket, Message}},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Json},
     routing::{get, post},
     Router,
 };
 use futures::{StreamExt, SinkExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::{
     collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    os::unix::process::CommandExt,
     process::Stdio,
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tokio::{
+    io::{AsyncBufReadExt, BufReader},
     process::Command,
-    sync::{Mutex, RwLock},
+    sync::{broadcast, Mutex, RwLock},
 };
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -23,11 +33,364 @@ use tower_http::{
 use tracing::{info, warn};
 use uuid::Uuid;
 
+/// Matches yt-dlp's `[download]  42.3% of 5.21MiB at 1.20MiB/s` progress lines.
+static PROGRESS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\[download\]\s+(\d+(?:\.\d+)?)%").expect("valid progress regex")
+});
+
 // State and types
 #[derive(Clone)]
 struct AppState {
     jobs: Arc<RwLock<HashMap<Uuid, DownloadJob>>>,
     po_server_pid: Arc<Mutex<Option<u32>>>,
+    events: broadcast::Sender<JobEvent>,
+    rate_limiter: Arc<RateLimiter>,
+    backend: Arc<BackendConfig>,
+    db: Arc<Mutex<Connection>>,
+    notifiers: Arc<Vec<Box<dyn Notifier>>>,
+    /// PID of each running job's child, keyed by job id, so it can be cancelled or timed out.
+    children: Arc<Mutex<HashMap<Uuid, u32>>>,
+}
+
+/// Kills an entire process group (the download's child plus anything it spawned, e.g. ffmpeg).
+fn kill_process_group(pid: u32) {
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+/// Something that can be told about a job reaching a terminal state.
+#[async_trait::async_trait]
+trait Notifier: Send + Sync {
+    async fn notify(&self, job: &DownloadJob) -> Result<(), String>;
+}
+
+/// Posts a JSON payload to an arbitrary webhook URL.
+struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[async_trait::async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, job: &DownloadJob) -> Result<(), String> {
+        let body = json!({
+            "id": job.id,
+            "url": job.url,
+            "profile": job.profile,
+            "status": job.status,
+            "logs": job.logs.iter().rev().take(20).rev().collect::<Vec<_>>(),
+        });
+
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Sends a plain-text message to a Telegram chat via a bot.
+struct TelegramNotifier {
+    bot_token: String,
+    chat_id: String,
+    client: reqwest::Client,
+}
+
+#[async_trait::async_trait]
+impl Notifier for TelegramNotifier {
+    async fn notify(&self, job: &DownloadJob) -> Result<(), String> {
+        let tail: Vec<&String> = job.logs.iter().rev().take(5).collect();
+        let tail: Vec<&str> = tail.iter().rev().map(|s| s.as_str()).collect();
+        let text = format!(
+            "Job {} ({}) for {}\nStatus: {:?}\n{}",
+            job.id,
+            job.profile,
+            job.url,
+            job.status,
+            tail.join("\n"),
+        );
+
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
+        self.client
+            .post(&url)
+            .json(&json!({ "chat_id": self.chat_id, "text": text }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Builds the configured notifiers from env vars; any unset notifier is simply omitted.
+fn load_notifiers() -> Vec<Box<dyn Notifier>> {
+    let client = reqwest::Client::new();
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+    if let Ok(url) = std::env::var("YTDL_WEBHOOK_URL") {
+        notifiers.push(Box::new(WebhookNotifier { url, client: client.clone() }));
+    }
+
+    if let (Ok(bot_token), Ok(chat_id)) = (
+        std::env::var("YTDL_TELEGRAM_BOT_TOKEN"),
+        std::env::var("YTDL_TELEGRAM_CHAT_ID"),
+    ) {
+        notifiers.push(Box::new(TelegramNotifier { bot_token, chat_id, client }));
+    }
+
+    notifiers
+}
+
+/// Dispatches job-finished notifications to every configured notifier concurrently.
+async fn notify_job_finished(state: &AppState, job: &DownloadJob) {
+    let results = futures::future::join_all(state.notifiers.iter().map(|n| n.notify(job))).await;
+    for result in results {
+        if let Err(e) = result {
+            warn!("Notifier delivery failed for job {}: {}", job.id, e);
+        }
+    }
+}
+
+/// Path to the SQLite database backing job history; overridable via `YTDL_DB_PATH`.
+fn db_path() -> String {
+    std::env::var("YTDL_DB_PATH").unwrap_or_else(|_| "jobs.db".to_string())
+}
+
+/// Opens (creating if needed) the jobs database and ensures the schema exists.
+fn open_db() -> rusqlite::Result<Connection> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            id TEXT PRIMARY KEY,
+            url TEXT NOT NULL,
+            profile TEXT NOT NULL,
+            status TEXT NOT NULL,
+            progress REAL NOT NULL,
+            created_at TEXT NOT NULL,
+            logs TEXT NOT NULL
+        )",
+        (),
+    )?;
+    Ok(conn)
+}
+
+/// Inserts or updates a job's row to match its current in-memory state.
+fn save_job(conn: &Connection, job: &DownloadJob) -> rusqlite::Result<()> {
+    let logs = serde_json::to_string(&job.logs).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "INSERT INTO jobs (id, url, profile, status, progress, created_at, logs)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(id) DO UPDATE SET
+            status = excluded.status,
+            progress = excluded.progress,
+            logs = excluded.logs",
+        rusqlite::params![
+            job.id.to_string(),
+            job.url,
+            job.profile,
+            job.status.as_str(),
+            job.progress,
+            job.created_at.to_rfc3339(),
+            logs,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Loads every persisted job, for repopulating the in-memory map on startup.
+fn load_jobs(conn: &Connection) -> rusqlite::Result<Vec<DownloadJob>> {
+    let mut stmt = conn.prepare("SELECT id, url, profile, status, progress, created_at, logs FROM jobs")?;
+    let rows = stmt.query_map((), |row| {
+        let id: String = row.get(0)?;
+        let status: String = row.get(3)?;
+        let created_at: String = row.get(5)?;
+        let logs: String = row.get(6)?;
+
+        Ok(DownloadJob {
+            id: Uuid::parse_str(&id).unwrap_or_else(|_| Uuid::nil()),
+            url: row.get(1)?,
+            profile: row.get(2)?,
+            status: JobStatus::from_db_str(&status),
+            progress: row.get(4)?,
+            created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+                .unwrap_or_else(|_| chrono::Utc::now()),
+            logs: serde_json::from_str(&logs).unwrap_or_default(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Deletes jobs older than `max_age` that are in a terminal state, then reclaims disk space.
+fn cleanup_jobs(conn: &Connection, max_age: chrono::Duration) -> rusqlite::Result<usize> {
+    let cutoff = (chrono::Utc::now() - max_age).to_rfc3339();
+    let purged = conn.execute(
+        "DELETE FROM jobs WHERE created_at < ?1 AND status IN ('completed', 'failed')",
+        rusqlite::params![cutoff],
+    )?;
+    conn.execute("VACUUM", ())?;
+    Ok(purged)
+}
+
+/// Persists a job off the async task, since rusqlite is blocking and a single `Connection`
+/// is shared behind one lock — never call `save_job` directly from an async context.
+async fn persist_job(state: &AppState, job: DownloadJob) {
+    let db = state.db.clone();
+    let job_id = job.id;
+    let result = tokio::task::spawn_blocking(move || {
+        let conn = db.blocking_lock();
+        save_job(&conn, &job)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to persist job {}: {}", job_id, e),
+        Err(e) => warn!("Persist task for job {} panicked: {}", job_id, e),
+    }
+}
+
+/// Describes how to invoke the downloader backend (e.g. `yt-dlp` or a wrapper script).
+///
+/// Loaded once at startup from `config.toml` (or the path in `YTDL_CONFIG`), so deployments
+/// can point at a different interpreter, script, or working directory without recompiling.
+#[derive(Debug, Clone, Deserialize)]
+struct BackendConfig {
+    executable: String,
+    #[serde(default)]
+    script_args: Vec<String>,
+    working_directory: String,
+    /// Template args for a single download; `{url}` and `{profile}` are substituted in.
+    download_args: Vec<String>,
+    /// Template args for listing profiles.
+    profiles_args: Vec<String>,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig {
+            executable: "python".to_string(),
+            script_args: vec!["ytdl.py".to_string()],
+            working_directory: "/app".to_string(),
+            download_args: vec!["download".to_string(), "{url}".to_string(), "-p".to_string(), "{profile}".to_string()],
+            profiles_args: vec!["profiles".to_string()],
+        }
+    }
+}
+
+impl BackendConfig {
+    /// Loads `YTDL_CONFIG` (or `config.toml` if unset) if present, falling back to defaults.
+    async fn load() -> Self {
+        let path = std::env::var("YTDL_CONFIG").unwrap_or_else(|_| "config.toml".to_string());
+
+        match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => match toml::from_str(&contents) {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Failed to parse backend config at {}: {}", path, e);
+                    BackendConfig::default()
+                }
+            },
+            Err(_) => BackendConfig::default(),
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.executable);
+        command.args(&self.script_args).current_dir(&self.working_directory);
+        command
+    }
+
+    fn render_download_args(&self, url: &str, profile: &str) -> Vec<String> {
+        self.download_args
+            .iter()
+            .map(|arg| arg.replace("{url}", url).replace("{profile}", profile))
+            .collect()
+    }
+}
+
+/// Sliding-window per-IP rate limiter shared by the download and server-control endpoints.
+struct RateLimiter {
+    window: Duration,
+    max_requests: usize,
+    hits: Mutex<HashMap<IpAddr, Vec<Instant>>>,
+}
+
+impl RateLimiter {
+    fn from_env() -> Self {
+        let window_secs = std::env::var("YTDL_RATE_WINDOW_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let max_requests = std::env::var("YTDL_RATE_MAX")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+
+        RateLimiter {
+            window: Duration::from_secs(window_secs),
+            max_requests,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a hit for `ip` and returns whether it is within the allowed rate.
+    async fn check(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().await;
+
+        // Opportunistically sweep every tracked IP, not just the one being checked --
+        // otherwise an IP that stops sending requests is never revisited and its entry
+        // (and the Vec behind it) lives in the map forever.
+        hits.retain(|_, timestamps| {
+            timestamps.retain(|t| now.duration_since(*t) < self.window);
+            !timestamps.is_empty()
+        });
+
+        let timestamps = hits.entry(ip).or_insert_with(Vec::new);
+        if timestamps.len() >= self.max_requests {
+            false
+        } else {
+            timestamps.push(now);
+            true
+        }
+    }
+}
+
+/// Emitted whenever `run_download` mutates a job; forwarded live to connected websockets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum JobEvent {
+    JobCreated { job: DownloadJob },
+    ProgressUpdate { id: Uuid, progress: f32 },
+    LogLine { id: Uuid, line: String },
+    StatusChanged { id: Uuid, status: JobStatus },
+}
+
+impl JobEvent {
+    fn job_id(&self) -> Uuid {
+        match self {
+            JobEvent::JobCreated { job } => job.id,
+            JobEvent::ProgressUpdate { id, .. } => *id,
+            JobEvent::LogLine { id, .. } => *id,
+            JobEvent::StatusChanged { id, .. } => *id,
+        }
+    }
+}
+
+/// Commands a websocket client can send as a JSON text frame.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ClientCommand {
+    Subscribe(Uuid),
+    Cancel(Uuid),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +413,28 @@ enum JobStatus {
     Failed,
 }
 
+impl JobStatus {
+    /// Plain lowercase form, matching the `#[serde(rename_all = "lowercase")]` values but
+    /// without JSON's surrounding quotes — safe to use as a bare SQL string literal/column value.
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    fn from_db_str(s: &str) -> Self {
+        match s {
+            "pending" => JobStatus::Pending,
+            "running" => JobStatus::Running,
+            "completed" => JobStatus::Completed,
+            _ => JobStatus::Failed,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct DownloadRequest {
     url: String,
@@ -74,25 +459,45 @@ async fn main() {
 
     info!("Starting YT-Download-NG Web Backend");
 
+    let db = open_db().expect("failed to open jobs database");
+    let restored_jobs = load_jobs(&db).unwrap_or_else(|e| {
+        warn!("Failed to load persisted jobs: {}", e);
+        Vec::new()
+    });
+    info!("Restored {} job(s) from {}", restored_jobs.len(), db_path());
+    let jobs: HashMap<Uuid, DownloadJob> = restored_jobs.into_iter().map(|job| (job.id, job)).collect();
+
+    let (events, _) = broadcast::channel(256);
     let state = AppState {
-        jobs: Arc::new(RwLock::new(HashMap::new())),
+        jobs: Arc::new(RwLock::new(jobs)),
         po_server_pid: Arc::new(Mutex::new(None)),
+        events,
+        rate_limiter: Arc::new(RateLimiter::from_env()),
+        backend: Arc::new(BackendConfig::load().await),
+        db: Arc::new(Mutex::new(db)),
+        notifiers: Arc::new(load_notifiers()),
+        children: Arc::new(Mutex::new(HashMap::new())),
     };
 
     if let Err(e) = start_po_server(&state).await {
         warn!("Failed to start PO token server: {}", e);
     }
 
+    let rate_limited = Router::new()
+        .route("/api/download", post(start_download))
+        .route("/api/server/start", post(start_server))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit));
+
     let app = Router::new()
         .route("/", get(serve_ui))
         .route("/health", get(health_check))
         .route("/api/profiles", get(list_profiles))
-        .route("/api/download", post(start_download))
         .route("/api/jobs", get(list_jobs))
-        .route("/api/jobs/:id", get(get_job))
+        .route("/api/jobs/:id", get(get_job).delete(cancel_job_handler))
+        .route("/api/maintenance/cleanup", post(cleanup_maintenance))
         .route("/api/server/status", get(server_status))
-        .route("/api/server/start", post(start_server))
         .route("/ws", get(websocket_handler))
+        .merge(rate_limited)
         .nest_service("/static", ServeDir::new("static"))
         .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any))
         .layer(TraceLayer::new_for_http())
@@ -107,7 +512,34 @@ async fn main() {
     info!("Server listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
+}
+
+/// Rejects requests once a client IP exceeds `YTDL_RATE_MAX` hits within `YTDL_RATE_WINDOW_SECS`.
+async fn rate_limit(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> axum::response::Response {
+    if state.rate_limiter.check(addr.ip()).await {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ApiResponse::<()> {
+                success: false,
+                data: None,
+                error: Some("rate limited".to_string()),
+            }),
+        )
+            .into_response()
+    }
 }
 
 async fn serve_ui() -> impl IntoResponse {
@@ -125,11 +557,8 @@ async fn health_check() -> Json<ApiResponse<String>> {
     })
 }
 
-async fn list_profiles() -> Json<ApiResponse<Vec<String>>> {
-    let output = Command::new("python")
-        .args(&["ytdl.py", "profiles"])
-        .output()
-        .await;
+async fn list_profiles(State(state): State<AppState>) -> Json<ApiResponse<Vec<String>>> {
+    let output = state.backend.command().args(&state.backend.profiles_args).output().await;
 
     match output {
         Ok(output) if output.status.success() => {
@@ -181,8 +610,10 @@ async fn start_download(
 
     {
         let mut jobs = state.jobs.write().await;
-        jobs.insert(job_id, job);
+        jobs.insert(job_id, job.clone());
     }
+    persist_job(&state, job.clone()).await;
+    let _ = state.events.send(JobEvent::JobCreated { job });
 
     let state_clone = state.clone();
     let url = req.url;
@@ -228,6 +659,62 @@ async fn get_job(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct CleanupRequest {
+    /// Purge completed/failed jobs older than this many seconds (defaults to 30 days).
+    max_age_secs: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CleanupResponse {
+    purged: usize,
+}
+
+async fn cleanup_maintenance(
+    State(state): State<AppState>,
+    Json(req): Json<CleanupRequest>,
+) -> Json<ApiResponse<CleanupResponse>> {
+    let max_age = chrono::Duration::seconds(req.max_age_secs.unwrap_or(30 * 24 * 60 * 60));
+    let db = state.db.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let conn = db.blocking_lock();
+        cleanup_jobs(&conn, max_age)
+    })
+    .await;
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            return Json(ApiResponse {
+                success: false,
+                data: None,
+                error: Some(format!("Cleanup task panicked: {}", e)),
+            });
+        }
+    };
+
+    match result {
+        Ok(purged) => {
+            let mut jobs = state.jobs.write().await;
+            let cutoff = chrono::Utc::now() - max_age;
+            jobs.retain(|_, job| {
+                !matches!(job.status, JobStatus::Completed | JobStatus::Failed) || job.created_at >= cutoff
+            });
+
+            Json(ApiResponse {
+                success: true,
+                data: Some(CleanupResponse { purged }),
+                error: None,
+            })
+        }
+        Err(e) => Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some(format!("Cleanup failed: {}", e)),
+        }),
+    }
+}
+
 async fn server_status(State(state): State<AppState>) -> Json<ApiResponse<bool>> {
     let pid = state.po_server_pid.lock().await;
     let running = pid.is_some();
@@ -263,30 +750,93 @@ async fn websocket_handler(
 
 async fn websocket(stream: WebSocket, state: AppState) {
     let (mut sender, mut receiver) = stream.split();
+    let mut events = state.events.subscribe();
+    let mut subscribed_to: Option<Uuid> = None;
 
-    let state_clone = state.clone();
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Websocket lagged, skipped {} job events", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
 
-            let jobs = state_clone.jobs.read().await;
-            let job_list: Vec<DownloadJob> = jobs.values().cloned().collect();
+                if let Some(id) = subscribed_to {
+                    if event.job_id() != id {
+                        continue;
+                    }
+                }
 
-            if let Ok(msg) = serde_json::to_string(&job_list) {
-                if sender.send(Message::Text(msg)).await.is_err() {
-                    break;
+                if let Ok(msg) = serde_json::to_string(&event) {
+                    if sender.send(Message::Text(msg)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            msg = receiver.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break,
+                };
+
+                match msg {
+                    Message::Close(_) => break,
+                    Message::Text(text) => {
+                        match serde_json::from_str::<ClientCommand>(&text) {
+                            Ok(ClientCommand::Subscribe(id)) => subscribed_to = Some(id),
+                            Ok(ClientCommand::Cancel(id)) => cancel_job(&state, id).await,
+                            Err(e) => warn!("Ignoring malformed websocket command: {}", e),
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
-    });
+    }
+}
 
-    while let Some(Ok(msg)) = receiver.next().await {
-        if matches!(msg, Message::Close(_)) {
-            break;
+/// Kills a running job's process group and marks it failed, in response to a client `cancel`
+/// command or the `DELETE /api/jobs/:id` endpoint.
+/// Kills a running job's process group and marks it failed. Returns whether a running job
+/// was actually found and cancelled (it may have already finished by the time this runs).
+async fn cancel_job(state: &AppState, job_id: Uuid) -> bool {
+    let pid = state.children.lock().await.remove(&job_id);
+    match pid {
+        Some(pid) => {
+            kill_process_group(pid);
+            set_status(state, job_id, JobStatus::Failed).await;
+            push_log(state, job_id, "Cancelled by user".to_string()).await;
+            if let Some(job) = state.jobs.read().await.get(&job_id).cloned() {
+                notify_job_finished(state, &job).await;
+            }
+            true
+        }
+        None => {
+            warn!("Cancel requested for job {} which is not running", job_id);
+            false
         }
     }
 }
 
+async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Json<ApiResponse<()>> {
+    if cancel_job(&state, id).await {
+        Json(ApiResponse { success: true, data: None, error: None })
+    } else {
+        Json(ApiResponse {
+            success: false,
+            data: None,
+            error: Some("Job is not running".to_string()),
+        })
+    }
+}
+
 async fn start_po_server(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
     let mut child = Command::new("node")
         .arg("bgutil-pot-provider/server/build/main.js")
@@ -302,74 +852,225 @@ async fn start_po_server(state: &AppState) -> Result<(), Box<dyn std::error::Err
 }
 
 async fn run_download(state: AppState, job_id: Uuid, url: String, profile: String) {
-    {
-        let mut jobs = state.jobs.write().await;
-        if let Some(job) = jobs.get_mut(&job_id) {
-            job.status = JobStatus::Running;
-            job.logs.push(format!("Starting download: {}", url));
+    set_status(&state, job_id, JobStatus::Running).await;
+    push_log(&state, job_id, format!("Starting download: {}", url)).await;
+
+    let download_args = state.backend.render_download_args(&url, &profile);
+    let child = state
+        .backend
+        .command()
+        .args(&download_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0) // own process group, so yt-dlp's ffmpeg children die with it on cancel/timeout
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            set_status(&state, job_id, JobStatus::Failed).await;
+            push_log(&state, job_id, format!("Failed to execute command: {}", e)).await;
+            if let Some(job) = state.jobs.read().await.get(&job_id).cloned() {
+                notify_job_finished(&state, &job).await;
+            }
+            return;
         }
+    };
+
+    if let Some(pid) = child.id() {
+        state.children.lock().await.insert(job_id, pid);
     }
 
-    let output = Command::new("python")
-        .args(&["ytdl.py", "download", &url, "-p", &profile])
-        .current_dir("/app")
-        .output()
-        .await;
+    // A running download updates progress/logs on every output line, but writing that to
+    // SQLite on every line would do a blocking disk write per tick; snapshot periodically
+    // instead, and rely on `set_status`/`push_log` to persist the terminal state exactly.
+    let periodic_persist = tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            interval.tick().await; // first tick fires immediately; skip it
+            loop {
+                interval.tick().await;
+                if let Some(job) = state.jobs.read().await.get(&job_id).cloned() {
+                    persist_job(&state, job).await;
+                }
+            }
+        }
+    });
+
+    let timeout_secs = std::env::var("YTDL_JOB_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+    let result = tokio::time::timeout(
+        Duration::from_secs(timeout_secs),
+        drive_download(&state, job_id, &mut child),
+    )
+    .await;
 
-    {
-        let mut jobs = state.jobs.write().await;
-        if let Some(job) = jobs.get_mut(&job_id) {
-            match output {
-                Ok(output) if output.status.success() => {
-                    job.status = JobStatus::Completed;
-                    job.progress = 100.0;
-                    job.logs.push("Download completed successfully".to_string());
-                    
-                    // Add all stdout for debugging
-                    if !output.stdout.is_empty() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        for line in stdout.lines() {
-                            job.logs.push(format!("[stdout] {}", line));
-                        }
-                    }
-                    
-                    // Add stderr too in case there are warnings
-                    if !output.stderr.is_empty() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        for line in stderr.lines() {
-                            job.logs.push(format!("[stderr] {}", line));
+    periodic_persist.abort();
+    state.children.lock().await.remove(&job_id);
+
+    let already_finished = matches!(
+        state.jobs.read().await.get(&job_id).map(|job| &job.status),
+        Some(JobStatus::Completed) | Some(JobStatus::Failed)
+    );
+    if already_finished {
+        // Job was already cancelled concurrently; don't clobber that status/notification.
+        return;
+    }
+
+    match result {
+        Ok(status) => {
+            match status {
+                Ok(status) if status.success() => {
+                    {
+                        let mut jobs = state.jobs.write().await;
+                        if let Some(job) = jobs.get_mut(&job_id) {
+                            job.progress = 100.0;
                         }
                     }
+                    let _ = state.events.send(JobEvent::ProgressUpdate { id: job_id, progress: 100.0 });
+                    set_status(&state, job_id, JobStatus::Completed).await;
+                    push_log(&state, job_id, "Download completed successfully".to_string()).await;
                 }
-                Ok(output) => {
-                    job.status = JobStatus::Failed;
-                    job.logs.push(format!("Download failed with exit code: {}", output.status.code().unwrap_or(-1)));
-                    
-                    // Capture stderr for debugging
-                    if !output.stderr.is_empty() {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        for line in stderr.lines().take(10) {
-                            job.logs.push(format!("Error: {}", line));
-                        }
-                    }
-                    
-                    // Also capture stdout in case of error
-                    if !output.stdout.is_empty() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        for line in stdout.lines().take(5) {
-                            job.logs.push(format!("Output: {}", line));
-                        }
-                    }
+                Ok(status) => {
+                    set_status(&state, job_id, JobStatus::Failed).await;
+                    push_log(&state, job_id, format!("Download failed with exit code: {}", status.code().unwrap_or(-1))).await;
                 }
                 Err(e) => {
-                    job.status = JobStatus::Failed;
-                    job.logs.push(format!("Failed to execute command: {}", e));
+                    set_status(&state, job_id, JobStatus::Failed).await;
+                    push_log(&state, job_id, format!("Failed to wait on child process: {}", e)).await;
                 }
             }
         }
+        Err(_) => {
+            if let Some(pid) = child.id() {
+                kill_process_group(pid);
+            }
+            set_status(&state, job_id, JobStatus::Failed).await;
+            push_log(&state, job_id, format!("Job timed out after {}s", timeout_secs)).await;
+        }
+    }
+
+    if let Some(job) = state.jobs.read().await.get(&job_id).cloned() {
+        notify_job_finished(&state, &job).await;
     }
 }
 
+/// Reads the child's stdout/stderr to completion, updating the job as it goes, then waits for exit.
+async fn drive_download(
+    state: &AppState,
+    job_id: Uuid,
+    child: &mut tokio::process::Child,
+) -> std::io::Result<std::process::ExitStatus> {
+    let stdout = child.stdout.take().expect("piped stdout");
+    let stderr = child.stderr.take().expect("piped stderr");
+    let mut stdout_lines = BufReader::new(stdout).lines();
+    let mut stderr_lines = BufReader::new(stderr).lines();
+
+    loop {
+        tokio::select! {
+            line = stdout_lines.next_line() => {
+                match line {
+                    Ok(Some(line)) => handle_download_line(state, job_id, line).await,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("Error reading stdout for job {}: {}", job_id, e);
+                        break;
+                    }
+                }
+            }
+            line = stderr_lines.next_line() => {
+                match line {
+                    Ok(Some(line)) => handle_download_line(state, job_id, line).await,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("Error reading stderr for job {}: {}", job_id, e);
+                        continue;
+                    }
+                }
+            }
+        }
+    }
+
+    // Drain any remaining stderr output once stdout has closed.
+    while let Ok(Some(line)) = stderr_lines.next_line().await {
+        handle_download_line(state, job_id, line).await;
+    }
+
+    child.wait().await
+}
+
+/// Updates a job's progress if `line` is a yt-dlp progress line, otherwise appends it to the logs.
+async fn handle_download_line(state: &AppState, job_id: Uuid, line: String) {
+    let result = {
+        let mut jobs = state.jobs.write().await;
+        match jobs.get_mut(&job_id) {
+            Some(job) => match PROGRESS_RE.captures(&line) {
+                Some(captures) => captures
+                    .get(1)
+                    .and_then(|m| m.as_str().parse::<f32>().ok())
+                    .map(|percent| {
+                        job.progress = percent;
+                        (Some(percent), job.clone())
+                    }),
+                None => {
+                    job.logs.push(line.clone());
+                    Some((None, job.clone()))
+                }
+            },
+            None => return,
+        }
+    };
+
+    // Deliberately not persisted here: this runs once per stdout/stderr line, and a blocking
+    // SQLite write on every progress tick would serialize concurrent jobs. `run_download`
+    // periodically snapshots progress/logs to disk instead; status changes always persist.
+    let Some((progress, _job)) = result else { return };
+
+    match progress {
+        Some(percent) => {
+            let _ = state.events.send(JobEvent::ProgressUpdate { id: job_id, progress: percent });
+        }
+        None => {
+            let _ = state.events.send(JobEvent::LogLine { id: job_id, line });
+        }
+    }
+}
+
+/// Sets a job's status and publishes a `StatusChanged` event.
+async fn set_status(state: &AppState, job_id: Uuid, status: JobStatus) {
+    let job = {
+        let mut jobs = state.jobs.write().await;
+        match jobs.get_mut(&job_id) {
+            Some(job) => {
+                job.status = status.clone();
+                job.clone()
+            }
+            None => return,
+        }
+    };
+    persist_job(state, job).await;
+    let _ = state.events.send(JobEvent::StatusChanged { id: job_id, status });
+}
+
+/// Appends a log line to a job and publishes a `LogLine` event.
+async fn push_log(state: &AppState, job_id: Uuid, line: String) {
+    let job = {
+        let mut jobs = state.jobs.write().await;
+        match jobs.get_mut(&job_id) {
+            Some(job) => {
+                job.logs.push(line.clone());
+                job.clone()
+            }
+            None => return,
+        }
+    };
+    persist_job(state, job).await;
+    let _ = state.events.send(JobEvent::LogLine { id: job_id, line });
+}
+
 const UI_HTML: &str = r#"<!DOCTYPE html>
 <html lang="en">
 <head>